@@ -0,0 +1,74 @@
+// Structured, rotating audit log: one JSON object per line with an RFC
+// 3339 / ISO 8601 UTC timestamp, so the log stays machine-parseable and
+// the Pi's SD card can't silently fill up.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::AUTH_LOG;
+
+/// Roll `AUTH_LOG` to `AUTH_LOG.1` once it crosses this size.
+const MAX_LOG_BYTES: u64 = 5 * 1024 * 1024;
+
+/// Kind of event recorded in the audit log.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    AuthGranted,
+    AuthDenied,
+    SharingEnabled,
+    SharingDisabled,
+    Expired,
+    Cleanup,
+    SubprocessError,
+}
+
+#[derive(Debug, Serialize)]
+struct LogEntry<'a> {
+    timestamp: String,
+    event: EventKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uid: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<&'a str>,
+}
+
+/// Append one structured entry to the audit log, rotating first if the
+/// log has grown past `MAX_LOG_BYTES`.
+pub fn log_event(
+    event: EventKind,
+    uid: Option<&str>,
+    expires_at: Option<&str>,
+    context: Option<&str>,
+) -> io::Result<()> {
+    rotate_if_needed()?;
+
+    let entry = LogEntry {
+        timestamp: Utc::now().to_rfc3339(),
+        event,
+        uid,
+        expires_at,
+        context,
+    };
+
+    let line =
+        serde_json::to_string(&entry).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut file = OpenOptions::new().create(true).append(true).open(AUTH_LOG)?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+fn rotate_if_needed() -> io::Result<()> {
+    if let Ok(meta) = fs::metadata(AUTH_LOG) {
+        if meta.len() > MAX_LOG_BYTES {
+            fs::rename(AUTH_LOG, format!("{}.1", AUTH_LOG))?;
+        }
+    }
+    Ok(())
+}
@@ -1,4 +1,10 @@
-use std::fs::{self, File, OpenOptions};
+mod logging;
+mod nfc;
+mod policy;
+mod privileged;
+mod sessions;
+
+use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::Path;
 use std::process::Command;
@@ -8,15 +14,16 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use chrono::{NaiveDateTime, Local};
 
+use logging::EventKind;
+use nfc::{CardEvent, NfcReader};
+use policy::{CardPolicy, Policy};
+use privileged::PrivilegedCommand;
+
 // Constants
 const SHARE_PATH: &str = "/home/pi/file_share";
 const AUTH_LOG: &str = "/home/pi/nfc_auth.log";
 const AUTH_STATE: &str = "/home/pi/auth_state";
 const TIMEOUT_MINUTES: i64 = 10;
-const NFC_SCRIPT: &str = "/home/pi/nfc_detector.py";
-
-// List of authorized UIDs - replace with your actual card UIDs
-const AUTHORIZED_UIDS: [&str; 1] = ["79 DE 3F 02"];
 
 fn main() -> io::Result<()> {
     println!("\n===== Rust NFC File Sharing System =====");
@@ -24,18 +31,27 @@ fn main() -> io::Result<()> {
     println!("Press Ctrl+C to exit");
     println!("===================================\n");
 
-    // Ensure the script exists
-    if !Path::new(NFC_SCRIPT).exists() {
-        println!("Error: NFC detector script not found at {}", NFC_SCRIPT);
-        println!("Please create the script first");
-        return Ok(());
-    }
+    let mut reader = match NfcReader::new() {
+        Ok(reader) => reader,
+        Err(e) => {
+            println!("Error: could not open NFC reader: {}", e);
+            println!("Please connect a PC/SC compatible reader and try again");
+            return Ok(());
+        }
+    };
+
+    // Load the card ACL
+    let policy = Policy::load()?;
 
     // Ensure the share directory exists and system is setup
     setup_system()?;
 
-    // Ensure sharing is initially disabled
-    disable_file_sharing()?;
+    // Ensure sharing is initially disabled. A failure here shouldn't take
+    // the whole daemon down -- disable_file_sharing() already logs it and
+    // falls back to stopping smbd outright, so the gate still fails closed.
+    if let Err(e) = disable_file_sharing() {
+        println!("Warning: could not fully disable sharing at startup: {}", e);
+    }
 
     // Setup signal handling for clean shutdown
     let running = Arc::new(AtomicBool::new(true));
@@ -55,14 +71,17 @@ fn main() -> io::Result<()> {
             let now = SystemTime::now();
             if now.duration_since(last_check).unwrap_or(Duration::from_secs(0)) > Duration::from_secs(30) {
                 // Display status every 30 seconds
-                if let Ok(expiration_str) = fs::read_to_string(AUTH_STATE) {
-                    if let Ok(expiration) = NaiveDateTime::parse_from_str(&expiration_str.trim(), "%Y-%m-%d %H:%M:%S") {
+                if let Ok(state) = fs::read_to_string(AUTH_STATE) {
+                    let mut lines = state.lines();
+                    let expiration_str = lines.next().unwrap_or("");
+                    let holder = lines.next().unwrap_or("unknown card");
+                    if let Ok(expiration) = NaiveDateTime::parse_from_str(expiration_str.trim(), "%Y-%m-%d %H:%M:%S") {
                         let now = Local::now().naive_local();
                         if expiration > now {
                             let duration = expiration.signed_duration_since(now);
                             let mins = duration.num_minutes();
                             let secs = duration.num_seconds() % 60;
-                            println!("File sharing active. Time remaining: {:02}:{:02}", mins, secs);
+                            println!("File sharing active for {}. Time remaining: {:02}:{:02}", holder, mins, secs);
                         }
                     }
                 }
@@ -75,27 +94,47 @@ fn main() -> io::Result<()> {
         // Reset last_check when not authenticated
         last_check = SystemTime::now();
 
-        // Read NFC card using external Python script
-        match read_card_uid() {
-            Some(uid) => {
+        // Poll the reader for a card
+        match reader.poll() {
+            CardEvent::Uid(bytes) => {
+                let uid = nfc::format_uid(&bytes);
                 println!("\nCard detected: {}", uid);
-                
-                if AUTHORIZED_UIDS.contains(&uid.as_str()) {
-                    log_event(&format!("Authorized card: {}", uid))?;
-                    enable_file_sharing()?;
-                } else {
-                    log_event(&format!("Unauthorized card: {}", uid))?;
-                    println!("â— Unauthorized card");
-                    disable_file_sharing()?;
+
+                match policy.find(&uid) {
+                    Some(card) => {
+                        logging::log_event(EventKind::AuthGranted, Some(uid.as_str()), None, Some(card.name.as_str()))?;
+                        // A flaky privileged command or smbstatus call
+                        // shouldn't kill the daemon -- log and keep polling,
+                        // the grant just never took effect this round.
+                        if let Err(e) = enable_file_sharing(card) {
+                            println!("Warning: could not enable sharing for {}: {}", card.name, e);
+                        }
+                    }
+                    None => {
+                        logging::log_event(EventKind::AuthDenied, Some(uid.as_str()), None, None)?;
+                        println!("â— Unauthorized card");
+                        if let Err(e) = disable_file_sharing() {
+                            println!("Warning: could not fully disable sharing: {}", e);
+                        }
+                    }
                 }
-                
+
                 // Wait a moment before scanning again
                 thread::sleep(Duration::from_secs(2));
             },
-            None => {
+            CardEvent::NoCard => {
                 // Small delay to prevent CPU usage spikes
                 thread::sleep(Duration::from_millis(500));
             }
+            CardEvent::ReaderAbsent => {
+                println!("Warning: NFC reader not detected");
+                thread::sleep(Duration::from_secs(2));
+            }
+            CardEvent::TransmitError(e) => {
+                let msg = format!("NFC transmit error: {}", e);
+                logging::log_event(EventKind::SubprocessError, None, None, Some(msg.as_str()))?;
+                thread::sleep(Duration::from_millis(500));
+            }
         }
     }
 
@@ -105,26 +144,6 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-fn read_card_uid() -> Option<String> {
-    // Execute the Python script
-    match Command::new("sudo")
-        .arg("python3")
-        .arg(NFC_SCRIPT)
-        .output() {
-        Ok(output) => {
-            let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            
-            if !stdout.is_empty() && stdout != "NO_CARD" && 
-               stdout != "ERROR" && stdout != "NO_READERS" && 
-               stdout != "CONNECT_ERROR" && !stdout.starts_with("EXCEPTION:") {
-                return Some(stdout);
-            }
-            None
-        },
-        Err(_) => None
-    }
-}
-
 fn setup_system() -> io::Result<()> {
     println!("Setting up system requirements...");
     
@@ -201,130 +220,172 @@ fn setup_system() -> io::Result<()> {
     let _ = Command::new("sudo")
         .args(["usermod", "-L", "fileuser"])
         .status()?;
-    
+
+    // pi (the Samba user) needs to be in the fileuser group to pick up
+    // the group-level permissions enable_file_sharing() grants -- shares
+    // are owned root:fileuser, not pi, so role enforcement isn't bypassed
+    // by an unconditional owner bit
+    let _ = Command::new("sudo")
+        .args(["usermod", "-aG", "fileuser", "pi"])
+        .status()?;
+
     Ok(())
 }
 
-fn enable_file_sharing() -> io::Result<()> {
-    // Create state file with expiration time
+fn enable_file_sharing(card: &CardPolicy) -> io::Result<()> {
+    let timeout_minutes = card.timeout_minutes();
+    let share_dir = match card.share_dir() {
+        Ok(dir) => dir,
+        Err(msg) => {
+            logging::log_event(EventKind::SubprocessError, Some(card.uid.as_str()), None, Some(msg.as_str()))?;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        }
+    };
+
+    // Create state file with expiration time and the active card's identity
     let now = Local::now().naive_local();
-    let expiration = now + chrono::Duration::minutes(TIMEOUT_MINUTES);
-    
+    let expiration = now + chrono::Duration::minutes(timeout_minutes);
+
     // Format without timezone for simpler parsing
     let expiration_str = expiration.format("%Y-%m-%d %H:%M:%S").to_string();
-    
-    fs::write(AUTH_STATE, expiration_str)?;
-    
-    // Make the share directory accessible for Samba
-    let _ = Command::new("sudo")
-        .args(["chmod", "-R", "777", SHARE_PATH])
-        .status()?;
-    
-    // Set up permissions for SFTP access
-    let _ = Command::new("sudo")
-        .args(["chown", "-R", "pi:fileuser", SHARE_PATH])
-        .status()?;
-    
-    let _ = Command::new("sudo")
-        .args(["chmod", "-R", "770", SHARE_PATH])
-        .status()?;
-    
-    // Enable the fileuser account
-    let _ = Command::new("sudo")
-        .args(["usermod", "-U", "fileuser"])
-        .status()?;
-    
-    // Make sure Samba service is running
-    let _ = Command::new("sudo")
-        .args(["systemctl", "restart", "smbd"])
-        .status()?;
-    
-    log_event(&format!("File sharing enabled for {} minutes", TIMEOUT_MINUTES))?;
-    
+
+    fs::write(AUTH_STATE, format!("{}\n{}\n{}", expiration_str, card.uid, card.name))?;
+
+    if !Path::new(&share_dir).exists() {
+        fs::create_dir_all(&share_dir)?;
+    }
+
+    // Ownership is root:fileuser, never pi -- so the unconditional owner
+    // rwx bit can't bypass a readonly role. pi (Samba) and fileuser (SFTP)
+    // both reach the directory only through the group bits chmod sets
+    // below, driven by the card's role.
+    let grant = (|| -> Result<(), privileged::SystemError> {
+        if share_dir != SHARE_PATH {
+            // The scoped directory nests under SHARE_PATH, possibly several
+            // levels deep. Grant SHARE_PATH and every directory between it
+            // and the scoped directory standing traversal (but not listing)
+            // for the fileuser group so a sub_path-scoped card can still
+            // reach its directory; disable_file_sharing()/cleanup() reset
+            // these back to a locked-down 700 when the grant ends.
+            let parents = Path::new(&share_dir)
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(SHARE_PATH));
+            for dir in parents {
+                let dir = dir.to_string_lossy();
+                PrivilegedCommand::new(["chown", "root:fileuser", &*dir]).run()?;
+                PrivilegedCommand::new(["chmod", "0710", &*dir]).run()?;
+            }
+        }
+        PrivilegedCommand::new(["chown", "-R", "root:fileuser", share_dir.as_str()]).run()?;
+        PrivilegedCommand::new(["chmod", "-R", card.role.share_mode(), share_dir.as_str()]).run()?;
+        PrivilegedCommand::new(["usermod", "-U", "fileuser"]).run()?;
+        PrivilegedCommand::new(["systemctl", "restart", "smbd"]).run()?;
+        Ok(())
+    })();
+
+    if let Err(e) = grant {
+        let msg = e.to_string();
+        logging::log_event(EventKind::SubprocessError, Some(card.uid.as_str()), None, Some(msg.as_str()))?;
+        // Fail closed: don't leave the share in a partially-opened state
+        let _ = disable_file_sharing();
+        return Err(e.into());
+    }
+
+    logging::log_event(
+        EventKind::SharingEnabled,
+        Some(card.uid.as_str()),
+        Some(expiration_str.as_str()),
+        Some(card.name.as_str()),
+    )?;
+
     // Get IP address
     let ip_output = Command::new("hostname")
         .arg("-I")
         .output()?;
     let ip = String::from_utf8_lossy(&ip_output.stdout)
-        .trim()
         .split_whitespace()
         .next()
         .unwrap_or("localhost")
         .to_string();
-    
-    println!("\nâœ… File sharing ENABLED for {} minutes", TIMEOUT_MINUTES);
-    println!("ðŸ“ Shared folder: {}", SHARE_PATH);
+
+    println!("\nâœ… File sharing ENABLED for {} ({} minutes)", card.name, timeout_minutes);
+    println!("ðŸ“ Shared folder: {}", share_dir);
     println!("ðŸ’» Connect via SMB: smb://{}/FileShare", ip);
-    println!("ðŸ’» Connect via SFTP: sftp://{}/home/pi/file_share", ip);
+    println!("ðŸ’» Connect via SFTP: sftp://{}{}", ip, share_dir);
     println!("ðŸ‘¤ SMB Username: pi");
     println!("ðŸ‘¤ SFTP Username: fileuser");
     println!("â±ï¸  Timeout: {}", expiration.format("%H:%M:%S"));
-    
+
     Ok(())
 }
 
 fn disable_file_sharing() -> io::Result<()> {
     println!("Disabling file sharing...");
-    
+
+    let holder = fs::read_to_string(AUTH_STATE)
+        .ok()
+        .and_then(|state| state.lines().nth(1).map(str::to_string));
+
     if Path::new(AUTH_STATE).exists() {
         fs::remove_file(AUTH_STATE)?;
     }
-    
-    // Restrict permissions for both Samba and SFTP
-    let _ = Command::new("sudo")
-        .args(["chmod", "-R", "700", SHARE_PATH])
-        .status()?;
-    
-    let _ = Command::new("sudo")
-        .args(["chown", "-R", "pi:pi", SHARE_PATH])
-        .status()?;
-    
-    // Disable the fileuser account with stronger command
-    let _ = Command::new("sudo")
-        .args(["usermod", "-L", "fileuser"])
-        .status()?;
-    
-    // Force a restart of Samba to drop connections
-    let _ = Command::new("sudo")
-        .args(["systemctl", "restart", "smbd"])
-        .status()?;
-    
-    log_event("File sharing disabled");
+
+    // Restrict permissions for both Samba and SFTP, disable the fileuser
+    // account, then tear down live SMB and SSH connections directly
+    // instead of restarting smbd. The SMB sweep disconnects every client
+    // smbstatus reports, not only those using FileShare.
+    let lockdown = (|| -> Result<(), privileged::SystemError> {
+        PrivilegedCommand::new(["chmod", "-R", "700", SHARE_PATH]).run()?;
+        PrivilegedCommand::new(["chown", "-R", "pi:pi", SHARE_PATH]).run()?;
+        PrivilegedCommand::new(["usermod", "-L", "fileuser"]).run()?;
+        sessions::terminate_active_sessions()?;
+        Ok(())
+    })();
+
+    if let Err(e) = lockdown {
+        let msg = e.to_string();
+        logging::log_event(EventKind::SubprocessError, holder.as_deref(), None, Some(msg.as_str()))?;
+        // Fail closed: we can't be sure the lockdown took effect, so stop
+        // Samba outright rather than reporting "disabled"
+        let _ = PrivilegedCommand::new(["systemctl", "stop", "smbd"]).run();
+        return Err(e.into());
+    }
+
+    logging::log_event(EventKind::SharingDisabled, holder.as_deref(), None, None)?;
     println!("\nâŒ File sharing DISABLED");
-    
+
     Ok(())
 }
 
 fn cleanup() -> io::Result<()> {
     println!("Running complete cleanup...");
-    
-    // Restrict permissions extremely tightly
-    let _ = Command::new("sudo")
-        .args(["chmod", "-R", "700", SHARE_PATH])
-        .status()?;
-    
-    let _ = Command::new("sudo")
-        .args(["chown", "-R", "pi:pi", SHARE_PATH])
-        .status()?;
-    
-    // Ensure fileuser is locked
-    let _ = Command::new("sudo")
-        .args(["usermod", "-L", "fileuser"])
-        .status()?;
-    
-    // Stop Samba service to force disconnect all clients
-    let _ = Command::new("sudo")
-        .args(["systemctl", "restart", "smbd"])
-        .status()?;
-    
+
     // Remove the auth state file
     if Path::new(AUTH_STATE).exists() {
         fs::remove_file(AUTH_STATE)?;
     }
-    
-    log_event("System completely cleaned up on exit")?;
+
+    let lockdown = (|| -> Result<(), privileged::SystemError> {
+        PrivilegedCommand::new(["chmod", "-R", "700", SHARE_PATH]).run()?;
+        PrivilegedCommand::new(["chown", "-R", "pi:pi", SHARE_PATH]).run()?;
+        PrivilegedCommand::new(["usermod", "-L", "fileuser"]).run()?;
+        sessions::terminate_active_sessions()?;
+        Ok(())
+    })();
+
+    if let Err(e) = lockdown {
+        let msg = e.to_string();
+        logging::log_event(EventKind::SubprocessError, None, None, Some(msg.as_str()))?;
+        // Fail closed: we can't be sure the lockdown took effect, so stop
+        // Samba outright rather than reporting cleanup succeeded
+        let _ = PrivilegedCommand::new(["systemctl", "stop", "smbd"]).run();
+        return Err(e.into());
+    }
+
+    logging::log_event(EventKind::Cleanup, None, None, Some("System completely cleaned up on exit"))?;
     println!("Cleanup complete, all access should be disabled");
-    
+
     Ok(())
 }
 
@@ -333,13 +394,23 @@ fn check_auth_state() -> io::Result<bool> {
         return Ok(false);
     }
     
-    let expiration_str = fs::read_to_string(AUTH_STATE)?;
-    
-    match NaiveDateTime::parse_from_str(&expiration_str.trim(), "%Y-%m-%d %H:%M:%S") {
+    let state = fs::read_to_string(AUTH_STATE)?;
+    let mut lines = state.lines();
+    let expiration_str = lines.next().unwrap_or("");
+    let uid = lines.next();
+
+    match NaiveDateTime::parse_from_str(expiration_str.trim(), "%Y-%m-%d %H:%M:%S") {
         Ok(expiration) => {
             let now = Local::now().naive_local();
             if now > expiration {
-                disable_file_sharing()?;
+                logging::log_event(EventKind::Expired, uid, Some(expiration_str), None)?;
+                // This runs from the poll loop, so a flaky
+                // smbstatus/privileged call here can't be allowed to
+                // take the daemon down -- log it and keep polling.
+                // The grant is expired either way, so report access ended.
+                if let Err(e) = disable_file_sharing() {
+                    println!("Warning: could not fully disable expired grant: {}", e);
+                }
                 Ok(false)
             } else {
                 Ok(true)
@@ -347,21 +418,10 @@ fn check_auth_state() -> io::Result<bool> {
         },
         Err(_) => {
             // Failed to parse expiration, so disable sharing
-            disable_file_sharing()?;
+            if let Err(e) = disable_file_sharing() {
+                println!("Warning: could not fully disable sharing after unreadable auth state: {}", e);
+            }
             Ok(false)
         }
     }
 }
-
-fn log_event(message: &str) -> io::Result<()> {
-    let now = Local::now();
-    let timestamp = now.format("%Y-%m-%d %H:%M:%S");
-    
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(AUTH_LOG)?;
-    
-    writeln!(file, "[{}] {}", timestamp, message)?;
-    Ok(())
-}
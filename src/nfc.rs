@@ -0,0 +1,143 @@
+// Native PC/SC card reading, with an optional shell-out fallback (behind the
+// `python_fallback` feature) for boards whose reader lacks a PC/SC driver.
+
+#[cfg(not(feature = "python_fallback"))]
+use std::ffi::CString;
+
+/// Outcome of a single poll of the reader.
+#[derive(Debug)]
+pub enum CardEvent {
+    /// A card is present; UID bytes as read from the GET DATA APDU.
+    Uid(Vec<u8>),
+    /// No card is currently on the reader.
+    NoCard,
+    /// No PC/SC reader is attached to the system.
+    ReaderAbsent,
+    /// The reader is attached but connecting to the card or the APDU
+    /// transmit failed with an error not covered above. Only ever
+    /// constructed by the native reader; the python_fallback path has no
+    /// way to distinguish a transmit error from any other failure.
+    #[allow(dead_code)]
+    TransmitError(pcsc::Error),
+}
+
+/// Render UID bytes as the space-separated hex string used throughout the
+/// rest of this program (e.g. "79 DE 3F 02").
+pub fn format_uid(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// GET DATA: retrieve the UID of the card currently on the reader.
+#[cfg(not(feature = "python_fallback"))]
+const GET_UID_APDU: [u8; 5] = [0xFF, 0xCA, 0x00, 0x00, 0x00];
+
+/// Holds a persistent connection to a single PC/SC reader so polling does
+/// not pay the cost of re-establishing a context, or forking a process,
+/// on every cycle.
+#[cfg(not(feature = "python_fallback"))]
+pub struct NfcReader {
+    ctx: pcsc::Context,
+    reader_name: CString,
+    card: Option<pcsc::Card>,
+}
+
+#[cfg(not(feature = "python_fallback"))]
+impl NfcReader {
+    /// Connect to the first PC/SC reader the system reports.
+    pub fn new() -> Result<Self, pcsc::Error> {
+        let ctx = pcsc::Context::establish(pcsc::Scope::User)?;
+        let reader_name = first_reader_name(&ctx)?;
+        Ok(Self {
+            ctx,
+            reader_name,
+            card: None,
+        })
+    }
+
+    /// Poll the reader once, returning the current card state.
+    pub fn poll(&mut self) -> CardEvent {
+        if self.card.is_none() {
+            match self
+                .ctx
+                .connect(&self.reader_name, pcsc::ShareMode::Shared, pcsc::Protocols::ANY)
+            {
+                Ok(card) => self.card = Some(card),
+                Err(pcsc::Error::NoSmartcard) | Err(pcsc::Error::RemovedCard) => {
+                    return CardEvent::NoCard
+                }
+                Err(pcsc::Error::NoReadersAvailable) | Err(pcsc::Error::UnknownReader) => {
+                    return CardEvent::ReaderAbsent
+                }
+                Err(e) => return CardEvent::TransmitError(e),
+            }
+        }
+
+        let card = self.card.as_ref().expect("connected above");
+        let mut response_buf = [0; pcsc::MAX_BUFFER_SIZE];
+        match card.transmit(&GET_UID_APDU, &mut response_buf) {
+            // Trailing two bytes are the APDU status word (e.g. 90 00), not UID.
+            Ok(response) if response.len() > 2 => {
+                CardEvent::Uid(response[..response.len() - 2].to_vec())
+            }
+            Ok(_) => CardEvent::NoCard,
+            Err(pcsc::Error::RemovedCard) => {
+                self.card = None;
+                CardEvent::NoCard
+            }
+            Err(e) => CardEvent::TransmitError(e),
+        }
+    }
+}
+
+#[cfg(not(feature = "python_fallback"))]
+fn first_reader_name(ctx: &pcsc::Context) -> Result<CString, pcsc::Error> {
+    let mut buf = [0; 2048];
+    let mut readers = ctx.list_readers(&mut buf)?;
+    readers
+        .next()
+        .map(|r| r.to_owned())
+        .ok_or(pcsc::Error::NoReadersAvailable)
+}
+
+/// Shell-out fallback for boards whose PC/SC driver isn't available. Spawns
+/// the legacy `nfc_detector.py` helper on every poll, same as before this
+/// module existed.
+#[cfg(feature = "python_fallback")]
+pub struct NfcReader;
+
+#[cfg(feature = "python_fallback")]
+impl NfcReader {
+    pub fn new() -> Result<Self, pcsc::Error> {
+        Ok(Self)
+    }
+
+    pub fn poll(&mut self) -> CardEvent {
+        use std::process::Command;
+
+        const NFC_SCRIPT: &str = "/home/pi/nfc_detector.py";
+
+        match Command::new("sudo").arg("python3").arg(NFC_SCRIPT).output() {
+            Ok(output) => {
+                let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                match stdout.as_str() {
+                    "" | "NO_CARD" | "ERROR" | "CONNECT_ERROR" => CardEvent::NoCard,
+                    "NO_READERS" => CardEvent::ReaderAbsent,
+                    s if s.starts_with("EXCEPTION:") => CardEvent::NoCard,
+                    uid => CardEvent::Uid(parse_uid_string(uid)),
+                }
+            }
+            Err(_) => CardEvent::ReaderAbsent,
+        }
+    }
+}
+
+#[cfg(feature = "python_fallback")]
+fn parse_uid_string(s: &str) -> Vec<u8> {
+    s.split_whitespace()
+        .filter_map(|b| u8::from_str_radix(b, 16).ok())
+        .collect()
+}
@@ -0,0 +1,163 @@
+// Per-card access policy loaded from a config file, so adding, disabling,
+// or re-scoping a card doesn't require a recompile.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{SHARE_PATH, TIMEOUT_MINUTES};
+
+const POLICY_PATH: &str = "/home/pi/nfc_policy.toml";
+
+/// What a card is allowed to do once its grant is active.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Readwrite,
+    Readonly,
+}
+
+impl Role {
+    /// chmod mask applied to the share directory while this card's grant is
+    /// active. The owner bit is always `0`: `enable_file_sharing()` chowns
+    /// the directory to `root:fileuser`, so pi (the Samba user) and fileuser
+    /// (the SFTP user) only ever get in through the group bits below --
+    /// readonly cards genuinely can't write, rather than writing in as the
+    /// unconstrained owner.
+    pub fn share_mode(self) -> &'static str {
+        match self {
+            Role::Admin => "0070",
+            Role::Readwrite => "0070",
+            Role::Readonly => "0050",
+        }
+    }
+}
+
+/// One entry in the card policy file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CardPolicy {
+    pub uid: String,
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub role: Role,
+    /// Overrides `TIMEOUT_MINUTES` for this card, if set.
+    pub timeout_minutes: Option<i64>,
+    /// Restricted sub-path under `SHARE_PATH` this card is scoped to, if any.
+    pub sub_path: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl CardPolicy {
+    /// Grant duration for this card, falling back to the global default.
+    pub fn timeout_minutes(&self) -> i64 {
+        self.timeout_minutes.unwrap_or(TIMEOUT_MINUTES)
+    }
+
+    /// Directory this card's grant exposes, validated to stay under
+    /// `SHARE_PATH` (rejects `..`/empty components so a misconfigured
+    /// `sub_path` can't point a privileged chown/chmod outside the share).
+    /// Admin cards always get the full share: `sub_path` scoping only
+    /// applies to readwrite/readonly cards.
+    pub fn share_dir(&self) -> Result<String, String> {
+        if self.role == Role::Admin {
+            return Ok(SHARE_PATH.to_string());
+        }
+
+        let sub = match &self.sub_path {
+            Some(sub) => sub,
+            None => return Ok(SHARE_PATH.to_string()),
+        };
+
+        let trimmed = sub.trim_start_matches('/');
+        if trimmed.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+            return Err(format!(
+                "card {} has an invalid sub_path {:?}: must be a plain relative path under {}",
+                self.uid, sub, SHARE_PATH
+            ));
+        }
+
+        Ok(format!("{}/{}", SHARE_PATH, trimmed))
+    }
+}
+
+/// The full set of cards this reader will accept.
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct Policy {
+    #[serde(default)]
+    pub cards: Vec<CardPolicy>,
+}
+
+impl Policy {
+    /// Load the policy file. If it doesn't exist yet, no cards are
+    /// authorized rather than failing startup outright.
+    pub fn load() -> io::Result<Self> {
+        if !Path::new(POLICY_PATH).exists() {
+            println!(
+                "Warning: no policy file at {}, no cards will be authorized",
+                POLICY_PATH
+            );
+            return Ok(Policy::default());
+        }
+
+        let contents = fs::read_to_string(POLICY_PATH)?;
+        toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Find the enabled policy entry matching a scanned UID, if any.
+    pub fn find(&self, uid: &str) -> Option<&CardPolicy> {
+        self.cards.iter().find(|c| c.enabled && c.uid == uid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(role: Role, sub_path: Option<&str>) -> CardPolicy {
+        CardPolicy {
+            uid: "DE AD BE EF".to_string(),
+            name: "test card".to_string(),
+            enabled: true,
+            role,
+            timeout_minutes: None,
+            sub_path: sub_path.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn share_dir_rejects_dotdot_component() {
+        let c = card(Role::Readwrite, Some("../../etc"));
+        assert!(c.share_dir().is_err());
+    }
+
+    #[test]
+    fn share_dir_strips_leading_slash() {
+        let c = card(Role::Readwrite, Some("/projects"));
+        assert_eq!(c.share_dir().unwrap(), format!("{}/projects", SHARE_PATH));
+    }
+
+    #[test]
+    fn share_dir_rejects_empty_segment() {
+        let c = card(Role::Readwrite, Some("projects//secret"));
+        assert!(c.share_dir().is_err());
+    }
+
+    #[test]
+    fn share_dir_accepts_valid_nested_sub_path() {
+        let c = card(Role::Readonly, Some("projects/shared"));
+        assert_eq!(c.share_dir().unwrap(), format!("{}/projects/shared", SHARE_PATH));
+    }
+
+    #[test]
+    fn share_dir_ignores_sub_path_for_admin() {
+        let c = card(Role::Admin, Some("../../etc"));
+        assert_eq!(c.share_dir().unwrap(), SHARE_PATH);
+    }
+}
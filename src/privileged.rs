@@ -0,0 +1,80 @@
+// Typed wrapper around privileged (sudo) subprocess calls, so a failed
+// chmod/chown/usermod/systemctl call is surfaced as an error instead of
+// silently swallowed -- critical for a gate that's supposed to lock
+// access down.
+
+use std::fmt;
+use std::io;
+use std::process::Command;
+
+/// Error from a privileged subprocess call: the command that was run, its
+/// exit code (if it ran to completion), and any captured stderr.
+#[derive(Debug)]
+pub struct SystemError {
+    pub command: String,
+    pub exit_code: Option<i32>,
+    pub stderr: String,
+}
+
+impl fmt::Display for SystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.exit_code {
+            Some(code) => write!(
+                f,
+                "`{}` exited with status {}: {}",
+                self.command,
+                code,
+                self.stderr.trim()
+            ),
+            None => write!(f, "`{}` failed to run: {}", self.command, self.stderr.trim()),
+        }
+    }
+}
+
+impl std::error::Error for SystemError {}
+
+impl From<SystemError> for io::Error {
+    fn from(e: SystemError) -> Self {
+        io::Error::other(e.to_string())
+    }
+}
+
+/// A privileged command invoked via `sudo`, run and checked in one step.
+pub struct PrivilegedCommand {
+    args: Vec<String>,
+}
+
+impl PrivilegedCommand {
+    pub fn new<I, S>(args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            args: args.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Run `sudo <args>`, returning `Ok(())` only if it exits successfully.
+    pub fn run(&self) -> Result<(), SystemError> {
+        let output = Command::new("sudo").args(&self.args).output().map_err(|e| SystemError {
+            command: self.command_str(),
+            exit_code: None,
+            stderr: e.to_string(),
+        })?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(SystemError {
+                command: self.command_str(),
+                exit_code: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            })
+        }
+    }
+
+    fn command_str(&self) -> String {
+        format!("sudo {}", self.args.join(" "))
+    }
+}
@@ -0,0 +1,192 @@
+// Active session enforcement: on expiry or cleanup, tear down live SMB
+// connections and `fileuser`-owned SFTP/SSH sessions directly instead of
+// bouncing the whole smbd service, which leaves an in-flight SFTP/SSH
+// session alive past the timeout. The SMB sweep is not limited to clients
+// of `FileShare`: every session `smbstatus` lists is disconnected.
+
+use std::process::Command;
+
+use crate::privileged::{PrivilegedCommand, SystemError};
+
+/// One live SMB connection as reported by `smbstatus --json`.
+struct SmbConnection {
+    pid: String,
+    client_ip: String,
+}
+
+/// For every live SMB session, close `FileShare` on its smbd process and
+/// kill all connections from its client IP, whichever share or user the
+/// session belongs to; then kill every `fileuser`-owned sshd session.
+pub fn terminate_active_sessions() -> Result<(), SystemError> {
+    // Keep tearing down the rest even if one connection or the ssh sweep
+    // fails (e.g. a client already disconnected), so a single stale pid
+    // can't leave every other session standing; surface the first error.
+    let mut first_err = None;
+
+    for conn in list_smb_connections()? {
+        if let Err(e) = close_smb_connection(&conn) {
+            first_err.get_or_insert(e);
+        }
+    }
+
+    if let Err(e) = kill_fileuser_ssh_sessions() {
+        first_err.get_or_insert(e);
+    }
+
+    match first_err {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn smbstatus_error(stderr: impl Into<String>, exit_code: Option<i32>) -> SystemError {
+    SystemError {
+        command: "smbstatus --json".to_string(),
+        exit_code,
+        stderr: stderr.into(),
+    }
+}
+
+fn list_smb_connections() -> Result<Vec<SmbConnection>, SystemError> {
+    let output = Command::new("smbstatus")
+        .arg("--json")
+        .output()
+        .map_err(|e| smbstatus_error(e.to_string(), None))?;
+
+    if !output.status.success() {
+        return Err(smbstatus_error(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+            output.status.code(),
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(parse_smb_connections(&stdout))
+}
+
+fn parse_smb_connections(json: &str) -> Vec<SmbConnection> {
+    let value: serde_json::Value = match serde_json::from_str(json) {
+        Ok(v) => v,
+        Err(_) => return Vec::new(),
+    };
+
+    value
+        .get("sessions")
+        .and_then(|s| s.as_object())
+        .map(|sessions| {
+            sessions
+                .values()
+                .filter_map(|session| {
+                    // smbstatus --json nests the pid under server_id; fall
+                    // back to a flat `pid` field for older Samba versions.
+                    let pid = session
+                        .get("server_id")
+                        .and_then(|sid| sid.get("pid"))
+                        .or_else(|| session.get("pid"))
+                        .and_then(json_to_string)?;
+                    let client_ip = session.get("remote_machine")?.as_str()?.to_string();
+                    Some(SmbConnection { pid, client_ip })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn json_to_string(value: &serde_json::Value) -> Option<String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .or_else(|| value.as_i64().map(|n| n.to_string()))
+}
+
+fn close_smb_connection(conn: &SmbConnection) -> Result<(), SystemError> {
+    PrivilegedCommand::new(["smbcontrol", conn.pid.as_str(), "close-share", "FileShare"]).run()?;
+    PrivilegedCommand::new(["smbcontrol", "smbd", "kill-client-ip", conn.client_ip.as_str()]).run()
+}
+
+/// Kill every sshd session owned by `fileuser` via `loginctl`, falling
+/// back to `pkill` on systems without a running systemd logind.
+fn kill_fileuser_ssh_sessions() -> Result<(), SystemError> {
+    if let Ok(output) = Command::new("loginctl").args(["list-sessions", "--no-legend"]).output() {
+        if output.status.success() {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            for line in stdout.lines() {
+                let mut fields = line.split_whitespace();
+                let session_id = fields.next();
+                // Columns: SESSION UID USER SEAT ...
+                let user = fields.nth(1);
+                if let (Some(session_id), Some("fileuser")) = (session_id, user) {
+                    PrivilegedCommand::new(["loginctl", "terminate-session", session_id]).run()?;
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    PrivilegedCommand::new(["pkill", "-u", "fileuser", "sshd"]).run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_server_id_pid() {
+        let json = r#"{
+            "sessions": {
+                "1": {
+                    "server_id": { "pid": "1234" },
+                    "remote_machine": "192.168.1.50"
+                }
+            }
+        }"#;
+
+        let conns = parse_smb_connections(json);
+        assert_eq!(conns.len(), 1);
+        assert_eq!(conns[0].pid, "1234");
+        assert_eq!(conns[0].client_ip, "192.168.1.50");
+    }
+
+    #[test]
+    fn falls_back_to_flat_pid_for_older_samba() {
+        let json = r#"{
+            "sessions": {
+                "1": {
+                    "pid": 5678,
+                    "remote_machine": "192.168.1.51"
+                }
+            }
+        }"#;
+
+        let conns = parse_smb_connections(json);
+        assert_eq!(conns.len(), 1);
+        assert_eq!(conns[0].pid, "5678");
+        assert_eq!(conns[0].client_ip, "192.168.1.51");
+    }
+
+    #[test]
+    fn skips_sessions_missing_remote_machine() {
+        let json = r#"{
+            "sessions": {
+                "1": { "server_id": { "pid": "1234" } },
+                "2": { "server_id": { "pid": "5678" }, "remote_machine": "192.168.1.52" }
+            }
+        }"#;
+
+        let conns = parse_smb_connections(json);
+        assert_eq!(conns.len(), 1);
+        assert_eq!(conns[0].pid, "5678");
+    }
+
+    #[test]
+    fn returns_empty_for_malformed_json() {
+        let conns = parse_smb_connections("not json at all");
+        assert!(conns.is_empty());
+    }
+
+    #[test]
+    fn returns_empty_when_sessions_key_missing() {
+        let conns = parse_smb_connections(r#"{"timestamp": "now"}"#);
+        assert!(conns.is_empty());
+    }
+}